@@ -0,0 +1,52 @@
+//! LDAP control types: `RawControl` for controls attached to an outgoing
+//! request, `Control` for controls recovered from a response.
+
+/// A control as attached to an outgoing request, not yet BER-encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawControl {
+    pub ctype: String,
+    pub crit: bool,
+    pub val: Option<Vec<u8>>,
+}
+
+/// The RFC 4533 Sync Request Control's `mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncRequestMode {
+    RefreshOnly = 1,
+    RefreshAndPersist = 3,
+}
+
+/// The per-entry state carried by a Sync State Control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    Present,
+    Add,
+    Modify,
+    Delete,
+}
+
+/// RFC 4533 Sync State Control, attached to a `SearchResultEntry`'s controls
+/// during a content-sync search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncStateControl {
+    pub state: SyncState,
+    pub entry_uuid: Vec<u8>,
+    pub cookie: Option<Vec<u8>>,
+}
+
+/// RFC 4533 Sync Done Control, attached to `searchResultDone` at the end of a
+/// `refreshOnly` sync. The caller persists `cookie` to resume the sync later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncDoneControl {
+    pub cookie: Option<Vec<u8>>,
+    pub refresh_deletes: bool,
+}
+
+/// A control recovered from an incoming PDU. Controls this crate doesn't give
+/// a dedicated parse come through as `Unrecognized` rather than being dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Control {
+    SyncState(SyncStateControl),
+    SyncDone(SyncDoneControl),
+    Unrecognized(RawControl),
+}
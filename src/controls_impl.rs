@@ -0,0 +1,212 @@
+use crate::controls::{
+    Control, RawControl, SyncDoneControl, SyncRequestMode, SyncState, SyncStateControl,
+};
+
+use lber::common::TagClass;
+use lber::parse::{parse_uint, Parser};
+use lber::structure::StructureTag;
+use lber::structures::{ASNTag, Boolean, Enumerated, OctetString, Sequence, Tag};
+use lber::universal::Types;
+use lber::write;
+use lber::{Consumer, ConsumerState, IResult, Input, Move};
+
+use bytes::BytesMut;
+
+/// RFC 4533 Sync Request Control, attached to a search request.
+pub const SYNC_REQUEST_OID: &str = "1.3.6.1.4.1.4203.1.9.1.1";
+/// RFC 4533 Sync State Control, attached to each search result entry.
+pub const SYNC_STATE_OID: &str = "1.3.6.1.4.1.4203.1.9.1.2";
+/// RFC 4533 Sync Done Control, attached to `searchResultDone`.
+pub const SYNC_DONE_OID: &str = "1.3.6.1.4.1.4203.1.9.1.3";
+/// RFC 4533 Sync Info Intermediate Response `responseName`.
+pub const SYNC_INFO_OID: &str = "1.3.6.1.4.1.4203.1.9.1.4";
+
+/// Build the Sync Request Control value: `SEQUENCE { mode ENUMERATED, cookie
+/// OCTET STRING OPTIONAL, reloadHint BOOLEAN DEFAULT FALSE }`, attached to a
+/// search request to start or resume an RFC 4533 content-sync search.
+pub fn build_sync_request_control(
+    mode: SyncRequestMode,
+    cookie: Option<&[u8]>,
+    reload_hint: bool,
+) -> RawControl {
+    let mut inner = vec![Tag::Enumerated(Enumerated {
+        inner: mode as i64,
+        ..Default::default()
+    })];
+    if let Some(cookie) = cookie {
+        inner.push(Tag::OctetString(OctetString {
+            inner: cookie.to_vec(),
+            ..Default::default()
+        }));
+    }
+    if reload_hint {
+        inner.push(Tag::Boolean(Boolean {
+            inner: true,
+            ..Default::default()
+        }));
+    }
+    let val = encode_sequence(inner);
+    RawControl {
+        ctype: SYNC_REQUEST_OID.to_string(),
+        crit: true,
+        val: Some(val),
+    }
+}
+
+fn encode_sequence(inner: Vec<Tag>) -> Vec<u8> {
+    let outstruct = Tag::Sequence(Sequence {
+        inner,
+        ..Default::default()
+    })
+    .into_structure();
+    let mut buf = BytesMut::new();
+    write::encode_into(&mut buf, outstruct).expect("encode control value");
+    buf.to_vec()
+}
+
+/// Encode a `RawControl` into its `Control ::= SEQUENCE { controlType
+/// LDAPOID, criticality BOOLEAN DEFAULT FALSE, controlValue OCTET STRING
+/// OPTIONAL }` wire form.
+pub(crate) fn build_tag(control: RawControl) -> StructureTag {
+    let mut inner = vec![Tag::OctetString(OctetString {
+        inner: control.ctype.into_bytes(),
+        ..Default::default()
+    })];
+    if control.crit {
+        inner.push(Tag::Boolean(Boolean {
+            inner: true,
+            ..Default::default()
+        }));
+    }
+    if let Some(val) = control.val {
+        inner.push(Tag::OctetString(OctetString {
+            inner: val,
+            ..Default::default()
+        }));
+    }
+    Tag::Sequence(Sequence {
+        inner,
+        ..Default::default()
+    })
+    .into_structure()
+}
+
+/// Parse the `Controls` SEQUENCE (tag `[0]` on the outer `LDAPMessage`) into
+/// the per-message `Vec<Control>`, recognizing the RFC 4533 sync controls and
+/// falling back to `Control::Unrecognized` for anything else.
+pub(crate) fn parse_controls(tag: StructureTag) -> Vec<Control> {
+    let children = match tag.expect_constructed() {
+        Some(children) => children,
+        None => return vec![],
+    };
+    children.into_iter().filter_map(parse_one_control).collect()
+}
+
+fn parse_one_control(tag: StructureTag) -> Option<Control> {
+    let mut fields = tag.expect_constructed()?.into_iter();
+    let ctype = String::from_utf8(fields.next()?.expect_primitive()?).ok()?;
+    let mut crit = false;
+    let mut val = None;
+    if let Some(next) = fields.next() {
+        if next.class == TagClass::Universal && next.id == Types::Boolean as u64 {
+            crit = next.expect_primitive()?.first().copied().unwrap_or(0) != 0;
+            val = fields.next().and_then(|t| t.expect_primitive());
+        } else {
+            val = next.expect_primitive();
+        }
+    }
+    let raw = RawControl { ctype, crit, val };
+    Some(match raw.ctype.as_str() {
+        SYNC_STATE_OID => parse_sync_state(&raw)
+            .map(Control::SyncState)
+            .unwrap_or(Control::Unrecognized(raw)),
+        SYNC_DONE_OID => parse_sync_done(&raw)
+            .map(Control::SyncDone)
+            .unwrap_or(Control::Unrecognized(raw)),
+        _ => Control::Unrecognized(raw),
+    })
+}
+
+/// Decode a standalone BER element (e.g. a control's `controlValue`, or the
+/// CHOICE embedded in a Sync Info Intermediate Response's `responseValue`)
+/// out of `bytes`, returning the single top-level tag.
+pub(crate) fn parse_embedded_element(bytes: &[u8]) -> Option<StructureTag> {
+    let mut buf = BytesMut::from(bytes);
+    let mut parser = Parser::new();
+    let (amt, tag) = match *parser.handle(Input::Element(&mut buf)) {
+        ConsumerState::Done(amt, ref tag) => (amt, tag.clone()),
+        _ => return None,
+    };
+    match amt {
+        Move::Consume(_) => Some(tag),
+        _ => None,
+    }
+}
+
+/// Like `parse_embedded_element`, but unwraps the top-level tag's
+/// constructed contents, for values that are themselves a SEQUENCE.
+fn parse_embedded_sequence(bytes: &[u8]) -> Option<Vec<StructureTag>> {
+    parse_embedded_element(bytes)?.expect_constructed()
+}
+
+fn parse_sync_state(raw: &RawControl) -> Option<SyncStateControl> {
+    let mut fields = parse_embedded_sequence(raw.val.as_ref()?)?.into_iter();
+    let state = match parse_uint(fields.next()?.expect_primitive()?.as_slice()) {
+        IResult::Done(_, n) => match n {
+            0 => SyncState::Present,
+            1 => SyncState::Add,
+            2 => SyncState::Modify,
+            3 => SyncState::Delete,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let entry_uuid = fields.next()?.expect_primitive()?;
+    let cookie = fields.next().and_then(|t| t.expect_primitive());
+    Some(SyncStateControl {
+        state,
+        entry_uuid,
+        cookie,
+    })
+}
+
+fn parse_sync_done(raw: &RawControl) -> Option<SyncDoneControl> {
+    let mut fields = parse_embedded_sequence(raw.val.as_ref()?)?.into_iter().peekable();
+    let cookie = match fields.peek() {
+        Some(t) if t.class == TagClass::Universal && t.id == Types::OctetString as u64 => {
+            fields.next().and_then(|t| t.expect_primitive())
+        }
+        _ => None,
+    };
+    let refresh_deletes = match fields.next() {
+        Some(t) => t.expect_primitive()?.first().copied().unwrap_or(0) != 0,
+        None => false,
+    };
+    Some(SyncDoneControl {
+        cookie,
+        refresh_deletes,
+    })
+}
+
+/// `BindResponse` carries an optional `serverSaslCreds [7]` field alongside
+/// the standard result fields. Rather than extend the result type for one
+/// operation, surface it as a synthetic control alongside whatever real
+/// controls arrived on the message.
+pub(crate) fn parse_bind_response(tag: StructureTag) -> Vec<Control> {
+    let fields = match tag.expect_constructed() {
+        Some(fields) => fields,
+        None => return vec![],
+    };
+    fields
+        .into_iter()
+        .find(|t| t.class == TagClass::Context && t.id == 7)
+        .and_then(|t| t.expect_primitive())
+        .map(|creds| {
+            vec![Control::Unrecognized(RawControl {
+                ctype: "serverSaslCreds".to_string(),
+                crit: false,
+                val: Some(creds),
+            })]
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,13 @@
+mod controls;
+mod controls_impl;
+mod protocol;
+mod search;
+
+pub use controls::{Control, RawControl, SyncDoneControl, SyncRequestMode, SyncState, SyncStateControl};
+pub use controls_impl::{build_sync_request_control, SYNC_DONE_OID, SYNC_INFO_OID, SYNC_REQUEST_OID, SYNC_STATE_OID};
+pub use protocol::{LdapCodec, LdapMsg, LdapOp, SaslSecurityLayer, SyncInfo};
+pub use search::SearchItem;
+
+/// Message id assigned to each outstanding LDAP request. `0` is reserved and
+/// never assigned to a request; it marks an unsolicited notification.
+pub type RequestId = i32;
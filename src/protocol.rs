@@ -1,7 +1,7 @@
 use std::io;
 
 use crate::{controls::{Control, RawControl}, controls_impl::parse_bind_response};
-use crate::controls_impl::{build_tag, parse_controls};
+use crate::controls_impl::{build_tag, parse_controls, parse_embedded_element, SYNC_INFO_OID};
 use crate::search::SearchItem;
 use crate::RequestId;
 
@@ -18,7 +18,114 @@ use bytes::{Buf, BytesMut};
 use tokio::sync::{mpsc, oneshot};
 use tokio_util::codec::{Decoder, Encoder};
 
-pub struct LdapCodec;
+/// Per-PDU transform installed once a SASL bind has negotiated a security
+/// layer (integrity or confidentiality, e.g. GSSAPI/Kerberos or DIGEST-MD5
+/// with a QOP of `auth-int`/`auth-conf`).
+///
+/// The mechanism hands the codec an implementation after the bind completes;
+/// from then on every outgoing PDU is passed through `wrap` and every incoming
+/// token through `unwrap`, per RFC 4422/2222 buffering.
+pub trait SaslSecurityLayer: Send {
+    /// Wrap a cleartext BER PDU, producing the token to be length-prefixed and
+    /// written on the wire.
+    fn wrap(&mut self, cleartext: &[u8]) -> io::Result<Vec<u8>>;
+    /// Unwrap a received token back into the cleartext BER it carried.
+    fn unwrap(&mut self, token: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// DIGEST-MD5's default `maxbuf` (RFC 2831), used until a bind negotiates a
+/// different security-layer buffer size.
+const DEFAULT_SASL_MAX_BUFFER: usize = 65_536;
+
+/// Largest PDU we will accept by default before rejecting it as a potential
+/// resource-exhaustion attempt. Generous enough for ordinary search results
+/// while bounding what a hostile or buggy peer can make us buffer.
+const DEFAULT_MAX_PDU_SIZE: usize = 8 * 1024 * 1024;
+
+pub struct LdapCodec {
+    /// Security-layer transform; `None` means pass-through raw BER.
+    sasl: Option<Box<dyn SaslSecurityLayer>>,
+    /// Cleartext recovered from `unwrap` that has not yet formed a full PDU.
+    recv: BytesMut,
+    /// Largest wrapped token we will accept before rejecting the frame.
+    max_sasl_buffer: usize,
+    /// Largest PDU (outer SEQUENCE, header plus contents) we will buffer.
+    max_pdu_size: usize,
+    /// Cap on the number of controls attached to a single message, or `None`
+    /// to leave it unbounded.
+    max_controls: Option<usize>,
+}
+
+impl Default for LdapCodec {
+    fn default() -> Self {
+        LdapCodec {
+            sasl: None,
+            recv: BytesMut::new(),
+            max_sasl_buffer: DEFAULT_SASL_MAX_BUFFER,
+            max_pdu_size: DEFAULT_MAX_PDU_SIZE,
+            max_controls: None,
+        }
+    }
+}
+
+impl LdapCodec {
+    /// A codec in pass-through mode with the default decode limits.
+    pub fn new() -> Self {
+        LdapCodec::default()
+    }
+
+    /// Set the largest PDU the decoder will buffer. A peer advertising an outer
+    /// SEQUENCE longer than this has `decode` fail with `InvalidData` rather than
+    /// continuing to accumulate bytes.
+    pub fn with_max_pdu_size(mut self, max_pdu_size: usize) -> Self {
+        self.max_pdu_size = max_pdu_size;
+        self
+    }
+
+    /// Set the maximum number of controls accepted on a single message.
+    pub fn with_max_controls(mut self, max_controls: usize) -> Self {
+        self.max_controls = Some(max_controls);
+        self
+    }
+
+    /// Install a negotiated SASL security layer, switching the codec out of
+    /// pass-through mode. `max_buffer` is the negotiated maximum wrapped-token
+    /// size; frames whose length prefix exceeds it are rejected on decode.
+    pub fn set_security_layer(&mut self, layer: Box<dyn SaslSecurityLayer>, max_buffer: usize) {
+        self.sasl = Some(layer);
+        self.max_sasl_buffer = max_buffer;
+    }
+}
+
+/// Read the total length (header plus contents) declared by the outer BER
+/// element at the front of `buf`, if enough of the length header is buffered to
+/// determine it. Returns `None` while the header is still incomplete, or for the
+/// indefinite-length form, which LDAP never uses.
+fn declared_pdu_len(buf: &[u8]) -> Option<usize> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let first = buf[1];
+    if first & 0x80 == 0 {
+        // Short form: the length fits in the single octet.
+        Some(2 + first as usize)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || buf.len() < 2 + n {
+            return None;
+        }
+        if n > core::mem::size_of::<usize>() {
+            // A length field wider than `usize` cannot be represented; treat it
+            // as exceeding any configured maximum rather than silently wrapping.
+            return Some(usize::MAX);
+        }
+        let mut len = 0usize;
+        for &b in &buf[2..2 + n] {
+            len = (len << 8) | b as usize;
+        }
+        Some(len.saturating_add(2 + n))
+    }
+}
 
 pub(crate) type MaybeControls = Option<Vec<RawControl>>;
 pub(crate) type ItemSender = mpsc::UnboundedSender<(SearchItem, Vec<Control>)>;
@@ -32,12 +139,210 @@ pub enum LdapOp {
     Unbind,
 }
 
+/// A message recovered from the wire by the codec.
+///
+/// Most PDUs are `Response`s, matched back to the request that produced them by
+/// message id. An unsolicited notification — an ExtendedResponse with message id
+/// 0, such as a Notice of Disconnection — is pushed by the server and belongs to
+/// no request, so it is surfaced as its own `Notification` event for a connection
+/// manager to act on rather than being forced through the request/response path.
+#[derive(Debug)]
+pub enum LdapMsg {
+    Response(RequestId, (Tag, Vec<Control>)),
+    Notification((Tag, Vec<Control>)),
+    /// An Intermediate Response (APPLICATION 25) delivered mid-operation, e.g. an
+    /// RFC 4533 Sync Info message during a refreshAndPersist search. It carries
+    /// the message id of the request it belongs to so it can be routed to that
+    /// operation's stream rather than completing it like a normal response.
+    Intermediate(RequestId, (Tag, Vec<Control>)),
+}
+
+/// The RFC 4533 Sync Info message, delivered as the `responseValue` of an
+/// Intermediate Response (`responseName` `1.3.6.1.4.1.4203.1.9.1.4`) during a
+/// `refreshAndPersist` search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncInfo {
+    /// `newcookie [0]`: an updated cookie with no accompanying state change.
+    NewCookie(Vec<u8>),
+    /// `refreshDelete [1]`: the refresh phase is removing entries not present
+    /// in a later `syncIdSet`.
+    RefreshDelete {
+        cookie: Option<Vec<u8>>,
+        refresh_done: bool,
+    },
+    /// `refreshPresent [2]`: the refresh phase is done, or a later
+    /// `syncIdSet` lists the surviving entries.
+    RefreshPresent {
+        cookie: Option<Vec<u8>>,
+        refresh_done: bool,
+    },
+    /// `syncIdSet [3]`: the entry UUIDs present (or, per `refresh_deletes`,
+    /// deleted) since the last cookie.
+    SyncIdSet {
+        cookie: Option<Vec<u8>>,
+        refresh_deletes: bool,
+        sync_uuids: Vec<Vec<u8>>,
+    },
+}
+
+impl SyncInfo {
+    /// Recover the `SyncInfo` carried by an Intermediate Response's
+    /// `protocolOp`, if its `responseName` is the Sync Info OID.
+    ///
+    /// Unlike `ExtendedResponse`, `IntermediateResponse ::= [APPLICATION 25]
+    /// SEQUENCE { responseName [0] LDAPOID OPTIONAL, responseValue [1] OCTET
+    /// STRING OPTIONAL }` has no `COMPONENTS OF LDAPResult` prefix, so these
+    /// fields sit at `[0]`/`[1]`, not the `[10]`/`[11]` used for
+    /// `ExtendedResponse::responseName`/`responseValue`.
+    pub fn from_intermediate(tag: &Tag) -> Option<SyncInfo> {
+        let protoop = match tag {
+            Tag::StructureTag(t) => t.clone(),
+            _ => return None,
+        };
+        let fields = protoop.expect_constructed()?;
+        let mut name = None;
+        let mut value = None;
+        for field in fields {
+            match (field.class, field.id) {
+                (TagClass::Context, 0) => name = field.expect_primitive(),
+                (TagClass::Context, 1) => value = field.expect_primitive(),
+                _ => {}
+            }
+        }
+        if name.as_deref() != Some(SYNC_INFO_OID.as_bytes()) {
+            return None;
+        }
+        SyncInfo::from_choice(parse_embedded_element(&value?)?)
+    }
+
+    fn from_choice(choice: StructureTag) -> Option<SyncInfo> {
+        match choice.id {
+            0 => Some(SyncInfo::NewCookie(choice.expect_primitive()?)),
+            1 | 2 => {
+                let is_delete = choice.id == 1;
+                let (cookie, refresh_done) =
+                    parse_refresh_fields(choice.expect_constructed().unwrap_or_default());
+                if is_delete {
+                    Some(SyncInfo::RefreshDelete {
+                        cookie,
+                        refresh_done,
+                    })
+                } else {
+                    Some(SyncInfo::RefreshPresent {
+                        cookie,
+                        refresh_done,
+                    })
+                }
+            }
+            3 => {
+                let mut fields = choice.expect_constructed()?.into_iter().peekable();
+                let cookie = take_octet_string(&mut fields);
+                let refresh_deletes = take_boolean(&mut fields).unwrap_or(false);
+                // `syncUUIDs SET OF OCTET STRING` is one constructed SET tag
+                // whose members are its children, not flat siblings of cookie
+                // and refreshDeletes.
+                let sync_uuids = fields
+                    .next()
+                    .and_then(|t| t.expect_constructed())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|t| t.expect_primitive())
+                    .collect();
+                Some(SyncInfo::SyncIdSet {
+                    cookie,
+                    refresh_deletes,
+                    sync_uuids,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn parse_refresh_fields(fields: Vec<StructureTag>) -> (Option<Vec<u8>>, bool) {
+    let mut fields = fields.into_iter().peekable();
+    let cookie = take_octet_string(&mut fields);
+    // `refreshDone` defaults to `TRUE` here, unlike the Sync Done Control's
+    // `refreshDeletes`, which defaults to `FALSE`.
+    let refresh_done = take_boolean(&mut fields).unwrap_or(true);
+    (cookie, refresh_done)
+}
+
+fn take_octet_string(
+    iter: &mut std::iter::Peekable<std::vec::IntoIter<StructureTag>>,
+) -> Option<Vec<u8>> {
+    match iter.peek() {
+        Some(t) if t.class == TagClass::Universal && t.id == Types::OctetString as u64 => {
+            iter.next().and_then(|t| t.expect_primitive())
+        }
+        _ => None,
+    }
+}
+
+fn take_boolean(iter: &mut std::iter::Peekable<std::vec::IntoIter<StructureTag>>) -> Option<bool> {
+    match iter.peek() {
+        Some(t) if t.class == TagClass::Universal && t.id == Types::Boolean as u64 => iter
+            .next()
+            .and_then(|t| t.expect_primitive())
+            .map(|b| b.first().copied().unwrap_or(0) != 0),
+        _ => None,
+    }
+}
+
 impl Decoder for LdapCodec {
-    type Item = (RequestId, (Tag, Vec<Control>));
+    type Item = LdapMsg;
     type Error = io::Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if self.sasl.is_none() {
+            return self.decode_ber(buf);
+        }
+        // SASL security layer active (RFC 4422/2222 framing): each PDU arrives as
+        // a 4-byte big-endian length prefix followed by a wrapped token. Pull out
+        // every complete token, unwrap it to cleartext, and accumulate the result
+        // before running the BER parser over the recovered bytes.
+        loop {
+            if buf.len() < 4 {
+                break;
+            }
+            let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+            if len > self.max_sasl_buffer {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "SASL wrapped token exceeds negotiated maximum buffer size",
+                ));
+            }
+            if buf.len() < 4 + len {
+                break;
+            }
+            buf.advance(4);
+            let token = buf.split_to(len);
+            let cleartext = self.sasl.as_mut().expect("security layer").unwrap(&token)?;
+            self.recv.extend_from_slice(&cleartext);
+        }
+        let mut recv = std::mem::take(&mut self.recv);
+        let res = self.decode_ber(&mut recv);
+        self.recv = recv;
+        res
+    }
+}
+
+impl LdapCodec {
+    /// Parse a single LDAP PDU of cleartext BER out of `buf`, returning `None`
+    /// until a whole PDU is buffered. Shared by the pass-through and SASL paths.
+    fn decode_ber(&self, buf: &mut BytesMut) -> Result<Option<LdapMsg>, io::Error> {
         let decoding_error = io::Error::new(io::ErrorKind::Other, "decoding error");
+        // Bound memory use before the parser accumulates: once the outer
+        // SEQUENCE's declared length is readable, reject anything larger than the
+        // configured maximum instead of buffering it.
+        if let Some(pdu_size) = declared_pdu_len(buf) {
+            if pdu_size > self.max_pdu_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "LDAP PDU exceeds configured maximum size",
+                ));
+            }
+        }
         let mut parser = Parser::new();
         let (amt, tag) = match *parser.handle(Input::Element(buf)) {
             ConsumerState::Continue(_) => return Ok(None),
@@ -74,9 +379,27 @@ impl Decoder for LdapCodec {
                 // AD incorrectly encodes Notice of Disconnection messages. The OID of the
                 // Unsolicited Notification should be part of the ExtendedResponse sequence
                 // but AD puts it outside, where the optional controls belong. This confuses
-                // our parser, which doesn't expect the extra sequence element at the end
-                // and crashes. This match arm thus ignores the element.
-                maybe_controls = tags.pop().expect("element");
+                // our parser, which doesn't expect the extra sequence element at the end.
+                //
+                // An unsolicited notification (message id 0, e.g. Notice of Disconnection
+                // OID 1.3.6.1.4.1.1466.20036) is an out-of-band, server-pushed frame that
+                // the connection manager needs to see so it can react. Rather than drop the
+                // leaked element, fold it back into the ExtendedResponse sequence so the OID
+                // and value survive and reach the application through the message-id-0 slot,
+                // exactly as they would for a spec-correct server.
+                let leaked_oid = maybe_controls;
+                let mut protoop = tags.pop().expect("element");
+                if let PL::C(ref mut inner) = protoop.payload {
+                    // responseName is [10]; keep it ahead of an already-present
+                    // responseValue [11] so the reconstructed ExtendedResponse
+                    // preserves spec field order.
+                    let pos = inner
+                        .iter()
+                        .position(|e| e.class == TagClass::Context && e.id == 11)
+                        .unwrap_or(inner.len());
+                    inner.insert(pos, leaked_oid);
+                }
+                maybe_controls = protoop;
                 false
             }
             _ => false,
@@ -90,6 +413,14 @@ impl Decoder for LdapCodec {
             Some(controls) => parse_controls(controls),
             None => vec![],
         };
+        if let Some(max_controls) = self.max_controls {
+            if controls.len() > max_controls {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "message carries more controls than the configured maximum",
+                ));
+            }
+        }
         let msgid = match parse_uint(
             tags.pop()
                 .expect("element")
@@ -104,12 +435,25 @@ impl Decoder for LdapCodec {
         };
 //        Ok(Some((msgid, (Tag::StructureTag(protoop), controls))))
 
-        match protoop.id {
+        let is_intermediate = protoop.id == 25;
+        let controls = match protoop.id {
             1 => {
-                let controls = parse_bind_response(protoop.clone());
-                Ok(Some((msgid, (Tag::StructureTag(protoop), controls))))
+                let mut controls = controls;
+                controls.extend(parse_bind_response(protoop.clone()));
+                controls
             }
-            _ => Ok(Some((msgid, (Tag::StructureTag(protoop), controls)))),
+            _ => controls,
+        };
+        let payload = (Tag::StructureTag(protoop), controls);
+        if msgid == 0 {
+            // Unsolicited notification: server-pushed, bound to no request.
+            Ok(Some(LdapMsg::Notification(payload)))
+        } else if is_intermediate {
+            // Intermediate Response (APPLICATION 25): progress within an
+            // in-flight operation, not its completion.
+            Ok(Some(LdapMsg::Intermediate(msgid, payload)))
+        } else {
+            Ok(Some(LdapMsg::Response(msgid, payload)))
         }
     }
 }
@@ -144,7 +488,27 @@ impl Encoder<(RequestId, Tag, MaybeControls)> for LdapCodec {
             })
             .into_structure()
         };
-        write::encode_into(into, outstruct)?;
+        match self.sasl.as_mut() {
+            None => write::encode_into(into, outstruct)?,
+            Some(layer) => {
+                // Encode the cleartext PDU, wrap it through the security layer, then
+                // frame it with a 4-byte big-endian length prefix (RFC 4422/2222).
+                let mut pdu = BytesMut::new();
+                write::encode_into(&mut pdu, outstruct)?;
+                let token = layer.wrap(&pdu)?;
+                // RFC 4422/2222 require the sender to honor the receiver's
+                // advertised maximum buffer size; reject a token that would
+                // overrun it rather than putting an un-decodable frame on the wire.
+                if token.len() > self.max_sasl_buffer {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "SASL wrapped token exceeds negotiated maximum buffer size",
+                    ));
+                }
+                into.extend_from_slice(&(token.len() as u32).to_be_bytes());
+                into.extend_from_slice(&token);
+            }
+        }
         Ok(())
     }
 }
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use crate::protocol::{ItemSender, LdapMsg, SyncInfo};
+use crate::RequestId;
+
+use lber::structures::Tag;
+
+/// An item delivered on a search's `ItemSender` stream.
+#[derive(Debug)]
+pub enum SearchItem {
+    Entry(Tag),
+    Referral(Tag),
+    Done(Tag),
+    /// An RFC 4533 Sync Info message, delivered out-of-band as an Intermediate
+    /// Response during a `refreshAndPersist` search. Like `Entry` and
+    /// `Referral`, and unlike `Done`, this doesn't complete the search; the
+    /// stream stays open for further entries and sync info.
+    SyncInfo(SyncInfo),
+}
+
+/// Route a decoded `LdapMsg` to the in-flight search it belongs to, or hand
+/// it back to the caller when it isn't search-specific.
+///
+/// This is the connection event loop's dispatch for every frame `LdapCodec`
+/// decodes: a `Response` (including ordinary search entries/done) is handed
+/// back to the caller to complete or forward to the matching
+/// request; a `Notification` has no request to route to and is always handed
+/// back; an `Intermediate` Sync Info message is forwarded on the search's
+/// `ItemSender` without completing it, and nothing is handed back to the
+/// caller since the operation stays in flight.
+pub(crate) fn route(
+    msg: LdapMsg,
+    searches: &HashMap<RequestId, ItemSender>,
+) -> Option<LdapMsg> {
+    match msg {
+        LdapMsg::Response(..) | LdapMsg::Notification(..) => Some(msg),
+        LdapMsg::Intermediate(id, (tag, controls)) => {
+            if let Some(info) = SyncInfo::from_intermediate(&tag) {
+                if let Some(tx) = searches.get(&id) {
+                    let _ = tx.send((SearchItem::SyncInfo(info), controls));
+                }
+            }
+            None
+        }
+    }
+}